@@ -15,7 +15,7 @@
 
 use std::{iter::zip, sync::Arc};
 
-use glam::{UVec2, Vec4};
+use glam::{Mat4, UVec2, Vec4};
 use rend3::{
     format_sso,
     graph::{
@@ -28,6 +28,7 @@ use rend3::{
 use wgpu::{BindGroup, Buffer};
 
 use crate::{
+    bloom,
     common::{self, CameraIndex},
     culling,
     forward::RoutineAddToGraphArgs,
@@ -75,6 +76,28 @@ impl DepthTargets {
     }
 }
 
+/// Selects how opaque/cutout objects are shaded by [`BaseRenderGraph::add_to_graph`].
+///
+/// Transparent/blend objects are always shaded on the forward path, regardless
+/// of this setting.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ShadingMode {
+    /// Shade opaque/cutout objects directly into the HDR color target as they're
+    /// rasterized. Simpler and cheaper on light scenes.
+    #[default]
+    Forward,
+    /// Rasterize opaque/cutout objects into a packed G-buffer, then shade every
+    /// covered pixel exactly once in a full-screen `deferred_lighting` pass.
+    /// Reduces shading overdraw on heavy scenes and gives user-inserted nodes
+    /// per-pixel material access before lighting runs.
+    ///
+    /// Not supported together with MSAA: a bitpacked `Rgba32Uint` G-buffer
+    /// can't be resolved down with a linear color blit the way `color` is,
+    /// so [`BaseRenderGraph::add_to_graph`] requires `samples ==
+    /// SampleCount::One` whenever this is selected.
+    Deferred,
+}
+
 /// Starter RenderGraph.
 ///
 /// See module for documentation.
@@ -83,6 +106,10 @@ pub struct BaseRenderGraph {
     pub samplers: common::Samplers,
     pub gpu_culler: culling::GpuCuller,
     pub gpu_skinner: skinning::GpuSkinner,
+    /// Names of the auxiliary bind groups registered with
+    /// [`Self::register_extra_bg`], in the order they'll be appended to every
+    /// forward draw's bind group list.
+    extra_bg_slots: Vec<Box<str>>,
 }
 
 impl BaseRenderGraph {
@@ -103,9 +130,26 @@ impl BaseRenderGraph {
             samplers,
             gpu_culler,
             gpu_skinner,
+            extra_bg_slots: Vec::new(),
         }
     }
 
+    /// Register a named auxiliary bind group slot (e.g. the hi-z depth
+    /// pyramid, the normal prepass target, or a user-supplied environment
+    /// probe) that will be appended, in registration order, to the bind
+    /// group list of every forward opaque/cutout/transparent draw call.
+    ///
+    /// Call this once up front. Each frame, [`BaseRenderGraphIntermediateState::new`]
+    /// creates one empty [`DataHandle`] per registered slot; look it up with
+    /// [`BaseRenderGraphIntermediateState::extra_bg`] and fill it from a graph
+    /// node (`ctx.graph_data.set_data`) before the forward passes run. The
+    /// stock `add_to_graph` creates that state internally and never fills the
+    /// slots, so with it unchanged the slots stay empty and forward draws skip
+    /// binding them.
+    pub fn register_extra_bg(&mut self, name: impl Into<Box<str>>) {
+        self.extra_bg_slots.push(name.into());
+    }
+
     /// Add this to the rendergraph. This is the function you should start
     /// customizing.
     #[allow(clippy::too_many_arguments)]
@@ -115,15 +159,22 @@ impl BaseRenderGraph {
         eval_output: &InstructionEvaluationOutput,
         pbr: &'node crate::pbr::PbrRoutine,
         skybox: Option<&'node crate::skybox::SkyboxRoutine>,
+        bloom: Option<&'node crate::bloom::BloomRoutine>,
         tonemapping: &'node crate::tonemapping::TonemappingRoutine,
         target_texture: RenderTargetHandle,
         resolution: UVec2,
         samples: SampleCount,
         ambient: Vec4,
         clear_color: Vec4,
+        shading_mode: ShadingMode,
+        /// Inverse of this frame's view-projection matrix. Only consumed by
+        /// [`ShadingMode::Deferred`], to reconstruct world position from
+        /// depth in the `deferred_lighting` pass; ignored in
+        /// [`ShadingMode::Forward`].
+        inverse_view_proj: Mat4,
     ) {
         // Create the data and handles for the graph.
-        let state = BaseRenderGraphIntermediateState::new(graph, eval_output, resolution, samples);
+        let state = BaseRenderGraphIntermediateState::new(graph, self, eval_output, resolution, samples);
 
         // Clear the shadow map.
         state.clear_shadow(graph);
@@ -148,21 +199,48 @@ impl BaseRenderGraph {
         // Upload the uniforms for the objects in the forward pass.
         state.object_uniform_upload(graph, self, resolution, samples);
 
-        // Do the first pass, rendering the predicted triangles from last frame.
-        state.pbr_render_opaque_predicted_triangles(graph, pbr, samples);
+        match shading_mode {
+            ShadingMode::Forward => {
+                // Do the first pass, rendering the predicted triangles from last frame.
+                state.pbr_render_opaque_predicted_triangles(graph, pbr, samples);
 
-        // Create the hi-z buffer.
-        state.hi_z(graph, pbr, resolution);
+                // Create the hi-z buffer.
+                state.hi_z(graph, pbr, resolution);
 
-        // Perform culling for the objects in the forward pass.
-        //
-        // The result of culling will be used to predict the visible triangles for
-        // the next frame. It will also render all the triangles that were visible
-        // but were not predicted last frame.
-        state.pbr_culling(graph, self);
+                // Perform culling for the objects in the forward pass.
+                //
+                // The result of culling will be used to predict the visible triangles for
+                // the next frame. It will also render all the triangles that were visible
+                // but were not predicted last frame.
+                state.pbr_culling(graph, self);
+
+                // Do the second pass, rendering the residual triangles.
+                state.pbr_render_opaque_residual_triangles(graph, pbr, samples);
+            }
+            ShadingMode::Deferred => {
+                assert_eq!(
+                    samples,
+                    SampleCount::One,
+                    "ShadingMode::Deferred doesn't support MSAA (got {samples:?} samples): a packed Rgba32Uint \
+                     gbuffer can't be resolved like a color target. Use ShadingMode::Forward, or render at native \
+                     resolution with SampleCount::One.",
+                );
 
-        // Do the second pass, rendering the residual triangles.
-        state.pbr_render_opaque_residual_triangles(graph, pbr, samples);
+                // Same two-pass predicted/residual occlusion scheme as the forward path,
+                // but rasterizing into the packed G-buffer instead of shading inline.
+                state.pbr_render_opaque_predicted_triangles_gbuffer(graph, pbr, samples);
+
+                state.hi_z(graph, pbr, resolution);
+
+                state.pbr_culling(graph, self);
+
+                state.pbr_render_opaque_residual_triangles_gbuffer(graph, pbr, samples);
+
+                // Unpack the gbuffer per-pixel and run the PBR lighting/shadow evaluation
+                // once per covered pixel, writing into the HDR color target.
+                state.deferred_lighting(graph, pbr, inverse_view_proj);
+            }
+        }
 
         // Render the skybox.
         state.skybox(graph, skybox, samples);
@@ -173,6 +251,9 @@ impl BaseRenderGraph {
         // considered "residual".
         state.pbr_forward_rendering_transparent(graph, pbr, samples);
 
+        // Add HDR emissive glow, right before tonemapping consumes the HDR buffer.
+        state.bloom(graph, bloom, resolution);
+
         // Tonemap the HDR inner buffer to the output buffer.
         state.tonemapping(graph, tonemapping, target_texture);
     }
@@ -193,12 +274,39 @@ pub struct BaseRenderGraphIntermediateState {
     pub color: RenderTargetHandle,
     pub resolve: Option<RenderTargetHandle>,
     pub depth: DepthTargets,
+    /// World-space normal of the opaque/cutout geometry, written alongside
+    /// [`Self::color`] in the forward passes. Useful as an input to
+    /// screen-space effects (SSAO, SSR, TAA) that need per-pixel geometry
+    /// without a full deferred pass.
+    pub normal: RenderTargetHandle,
+    /// Single-sample resolve of [`Self::normal`], present whenever `samples`
+    /// needs a resolve. This is the handle bound into `forward_uniform_bg`
+    /// and the one user-inserted nodes should sample from; [`Self::normal`]
+    /// itself may be multisampled.
+    pub normal_resolve: Option<RenderTargetHandle>,
+    /// Per-pixel screen-space motion between the previous and current frame,
+    /// written alongside [`Self::color`] in the forward passes. Primarily
+    /// intended as the reprojection input for temporal anti-aliasing.
+    pub motion_vectors: RenderTargetHandle,
+    /// Single-sample resolve of [`Self::motion_vectors`], present whenever
+    /// `samples` needs a resolve. Bind/sample this instead of
+    /// [`Self::motion_vectors`] directly.
+    pub motion_vectors_resolve: Option<RenderTargetHandle>,
+    /// Packed G-buffer used by [`ShadingMode::Deferred`]: base color, world
+    /// normal, metallic/roughness, and material flags, all bitpacked into a
+    /// single `Rgba32Uint` target. Unused (and left empty) in forward mode.
+    pub gbuffer: RenderTargetHandle,
+    /// Handles for the auxiliary bind groups registered with
+    /// [`BaseRenderGraph::register_extra_bg`], in registration order. Look
+    /// one up by name with [`Self::extra_bg`].
+    pub extra_bgs: Vec<DataHandle<BindGroup>>,
     pub pre_skinning_buffers: DataHandle<skinning::PreSkinningBuffers>,
 }
 impl BaseRenderGraphIntermediateState {
     /// Create the default setting for all state.
     pub fn new(
         graph: &mut RenderGraph<'_>,
+        base: &BaseRenderGraph,
         eval_output: &InstructionEvaluationOutput,
         resolution: UVec2,
         samples: SampleCount,
@@ -244,8 +352,78 @@ impl BaseRenderGraphIntermediateState {
         });
         let depth = DepthTargets::new(graph, resolution, samples);
 
+        // World-space normal prepass target, written by the opaque/cutout forward
+        // passes alongside color.
+        let normal = graph.add_render_target(RenderTargetDescriptor {
+            label: Some("normal".into()),
+            resolution,
+            depth: 1,
+            mip_levels: Some(1),
+            samples,
+            format: TextureFormat::Rgba16Float,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+
+        // Mirrors `color`/`resolve`: `normal` may be multisampled, but anything
+        // downstream (the forward uniform bind group, user-inserted nodes) needs a
+        // plain `texture_2d` to sample, so resolve it down whenever MSAA is on.
+        let normal_resolve = samples.needs_resolve().then(|| {
+            graph.add_render_target(RenderTargetDescriptor {
+                label: Some("normal resolve".into()),
+                resolution,
+                depth: 1,
+                mip_levels: Some(1),
+                samples: SampleCount::One,
+                format: TextureFormat::Rgba16Float,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            })
+        });
+
+        // Per-pixel current-vs-previous-frame clip position delta, used for
+        // temporal reprojection.
+        let motion_vectors = graph.add_render_target(RenderTargetDescriptor {
+            label: Some("motion vectors".into()),
+            resolution,
+            depth: 1,
+            mip_levels: Some(1),
+            samples,
+            format: TextureFormat::Rg16Float,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+        let motion_vectors_resolve = samples.needs_resolve().then(|| {
+            graph.add_render_target(RenderTargetDescriptor {
+                label: Some("motion vectors resolve".into()),
+                resolution,
+                depth: 1,
+                mip_levels: Some(1),
+                samples: SampleCount::One,
+                format: TextureFormat::Rg16Float,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            })
+        });
+
+        // Packed G-buffer for the deferred shading path. A single `Rgba32Uint`
+        // attachment is enough to hold base color, world normal,
+        // metallic/roughness, and material flags.
+        //
+        // Always single-sample, regardless of `samples`: a bitpacked uint target
+        // can't be resolved with a linear color blit the way `color` can, and
+        // `ShadingMode::Deferred` requires `samples == SampleCount::One` (see
+        // `add_to_graph`), so this never actually needs to be multisampled.
+        let gbuffer = graph.add_render_target(RenderTargetDescriptor {
+            label: Some("gbuffer".into()),
+            resolution,
+            depth: 1,
+            mip_levels: Some(1),
+            samples: SampleCount::One,
+            format: TextureFormat::Rgba32Uint,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+
         let pre_skinning_buffers = graph.add_data::<skinning::PreSkinningBuffers>();
 
+        let extra_bgs = base.extra_bg_slots.iter().map(|_| graph.add_data()).collect();
+
         Self {
             pre_cull: graph.add_data(),
             shadow_cull: {
@@ -261,10 +439,25 @@ impl BaseRenderGraphIntermediateState {
             color,
             resolve,
             depth,
+            normal,
+            normal_resolve,
+            motion_vectors,
+            motion_vectors_resolve,
+            gbuffer,
+            extra_bgs,
             pre_skinning_buffers,
         }
     }
 
+    /// Look up the [`DataHandle`] for an auxiliary bind group registered with
+    /// [`BaseRenderGraph::register_extra_bg`] under `name`.
+    ///
+    /// Returns `None` if no slot was registered with that name.
+    pub fn extra_bg(&self, base: &BaseRenderGraph, name: &str) -> Option<DataHandle<BindGroup>> {
+        let index = base.extra_bg_slots.iter().position(|slot| &**slot == name)?;
+        Some(self.extra_bgs[index])
+    }
+
     /// Create all the uniforms all the shaders in this graph need.
     pub fn create_frame_uniforms<'node>(
         &self,
@@ -278,6 +471,8 @@ impl BaseRenderGraphIntermediateState {
             self.shadow_uniform_bg,
             self.forward_uniform_bg,
             self.shadow,
+            self.normal_resolve.unwrap_or(self.normal),
+            self.motion_vectors_resolve.unwrap_or(self.motion_vectors),
             &base.interfaces,
             &base.samplers,
             ambient,
@@ -352,10 +547,17 @@ impl BaseRenderGraphIntermediateState {
 
     /// Clear all the targets to their needed values
     pub fn clear(&self, graph: &mut RenderGraph<'_>, clear_color: Vec4) {
-        crate::clear::add_clear_to_graph(
+        // The normal and motion vector prepass targets aren't written until the
+        // opaque/cutout passes run, so clear them alongside color/depth to a
+        // well-defined "no normal"/"no motion" value for any background pixels.
+        crate::clear::add_clear_to_graph_with_extra_targets(
             graph,
             Some(self.color),
             self.resolve,
+            &[
+                (self.normal, self.normal_resolve),
+                (self.motion_vectors, self.motion_vectors_resolve),
+            ],
             self.depth.rendering_target(),
             clear_color,
             0.0,
@@ -384,6 +586,10 @@ impl BaseRenderGraphIntermediateState {
                     camera: CameraIndex::Shadow(shadow_index as u32),
                     color: None,
                     resolve: None,
+                    normal: None,
+                    normal_resolve: None,
+                    motion_vectors: None,
+                    motion_vectors_resolve: None,
                     depth: self
                         .shadow
                         .set_viewport(ViewportRect::new(desc.map.offset, UVec2::splat(desc.map.size))),
@@ -425,12 +631,16 @@ impl BaseRenderGraphIntermediateState {
                 whole_frame_uniform_bg: self.forward_uniform_bg,
                 culling_output_handle: None,
                 per_material: &pbr.per_material,
-                extra_bgs: None,
+                extra_bgs: Some(&self.extra_bgs),
                 label: "PBR Forward Pass 1",
                 samples,
                 camera: CameraIndex::Viewport,
                 color: Some(self.color),
                 resolve: self.resolve,
+                normal: Some(self.normal),
+                normal_resolve: self.normal_resolve,
+                motion_vectors: Some(self.motion_vectors),
+                motion_vectors_resolve: self.motion_vectors_resolve,
                 depth: self.depth.rendering_target(),
             });
         }
@@ -450,17 +660,109 @@ impl BaseRenderGraphIntermediateState {
                 whole_frame_uniform_bg: self.forward_uniform_bg,
                 culling_output_handle: Some(self.cull),
                 per_material: &pbr.per_material,
-                extra_bgs: None,
+                extra_bgs: Some(&self.extra_bgs),
                 label: "PBR Forward Pass 2",
                 samples,
                 camera: CameraIndex::Viewport,
                 color: Some(self.color),
                 resolve: self.resolve,
+                normal: Some(self.normal),
+                normal_resolve: self.normal_resolve,
+                motion_vectors: Some(self.motion_vectors),
+                motion_vectors_resolve: self.motion_vectors_resolve,
                 depth: self.depth.rendering_target(),
             });
         }
     }
 
+    /// Rasterize the PBR materials into the packed gbuffer, for
+    /// [`ShadingMode::Deferred`]. Mirrors [`Self::pbr_render_opaque_predicted_triangles`],
+    /// but packs the material into [`Self::gbuffer`] instead of shading inline.
+    pub fn pbr_render_opaque_predicted_triangles_gbuffer<'node>(
+        &self,
+        graph: &mut RenderGraph<'node>,
+        pbr: &'node pbr::PbrRoutine,
+        samples: SampleCount,
+    ) {
+        let routines = [&pbr.opaque_gbuffer_routine, &pbr.cutout_gbuffer_routine];
+        for routine in routines {
+            routine.add_forward_to_graph(RoutineAddToGraphArgs {
+                graph,
+                whole_frame_uniform_bg: self.forward_uniform_bg,
+                culling_output_handle: None,
+                per_material: &pbr.per_material,
+                extra_bgs: Some(&self.extra_bgs),
+                label: "PBR Gbuffer Pass 1",
+                samples,
+                camera: CameraIndex::Viewport,
+                color: Some(self.gbuffer),
+                resolve: None,
+                // World-space normal is already packed into `gbuffer` itself (see
+                // `gbuffer.wgsl`), so a separate attachment here would be redundant.
+                normal: None,
+                normal_resolve: None,
+                motion_vectors: None,
+                motion_vectors_resolve: None,
+                depth: self.depth.rendering_target(),
+            });
+        }
+    }
+
+    /// Rasterize the PBR materials into the packed gbuffer, for
+    /// [`ShadingMode::Deferred`]. Mirrors [`Self::pbr_render_opaque_residual_triangles`],
+    /// but packs the material into [`Self::gbuffer`] instead of shading inline.
+    pub fn pbr_render_opaque_residual_triangles_gbuffer<'node>(
+        &self,
+        graph: &mut RenderGraph<'node>,
+        pbr: &'node pbr::PbrRoutine,
+        samples: SampleCount,
+    ) {
+        let routines = [&pbr.opaque_gbuffer_routine, &pbr.cutout_gbuffer_routine];
+        for routine in routines {
+            routine.add_forward_to_graph(RoutineAddToGraphArgs {
+                graph,
+                whole_frame_uniform_bg: self.forward_uniform_bg,
+                culling_output_handle: Some(self.cull),
+                per_material: &pbr.per_material,
+                extra_bgs: Some(&self.extra_bgs),
+                label: "PBR Gbuffer Pass 2",
+                samples,
+                camera: CameraIndex::Viewport,
+                color: Some(self.gbuffer),
+                resolve: None,
+                // See the predicted-triangles gbuffer pass above: normal is
+                // already packed into `gbuffer`.
+                normal: None,
+                normal_resolve: None,
+                motion_vectors: None,
+                motion_vectors_resolve: None,
+                depth: self.depth.rendering_target(),
+            });
+        }
+    }
+
+    /// Unpack the gbuffer per-pixel and run the PBR lighting/shadow evaluation,
+    /// writing the result into [`Self::color`]. The counterpart of the forward
+    /// shading done inline by [`Self::pbr_render_opaque_predicted_triangles`]/
+    /// [`Self::pbr_render_opaque_residual_triangles`].
+    pub fn deferred_lighting<'node>(
+        &self,
+        graph: &mut RenderGraph<'node>,
+        pbr: &'node pbr::PbrRoutine,
+        inverse_view_proj: Mat4,
+    ) {
+        pbr.deferred_lighting_routine.add_to_graph(
+            graph,
+            self.gbuffer,
+            self.depth.rendering_target(),
+            self.color,
+            self.resolve,
+            self.shadow_uniform_bg,
+            self.forward_uniform_bg,
+            inverse_view_proj,
+        );
+    }
+
     /// Render the PBR materials.
     pub fn pbr_forward_rendering_transparent<'node>(
         &self,
@@ -473,12 +775,16 @@ impl BaseRenderGraphIntermediateState {
             whole_frame_uniform_bg: self.forward_uniform_bg,
             culling_output_handle: Some(self.cull),
             per_material: &pbr.per_material,
-            extra_bgs: None,
+            extra_bgs: Some(&self.extra_bgs),
             label: "PBR Forward",
             camera: CameraIndex::Viewport,
             samples,
             color: Some(self.color),
             resolve: self.resolve,
+            normal: None,
+            normal_resolve: None,
+            motion_vectors: None,
+            motion_vectors_resolve: None,
             depth: self.depth.rendering_target(),
         });
     }
@@ -487,6 +793,19 @@ impl BaseRenderGraphIntermediateState {
         pbr.hi_z.add_hi_z_to_graph(graph, self.depth, resolution);
     }
 
+    /// Add HDR emissive glow to the scene color, reading and writing
+    /// [`Self::resolve`] (or [`Self::color`] if there's no MSAA resolve target).
+    pub fn bloom<'node>(
+        &self,
+        graph: &mut RenderGraph<'node>,
+        bloom: Option<&'node bloom::BloomRoutine>,
+        resolution: UVec2,
+    ) {
+        if let Some(bloom) = bloom {
+            bloom.add_to_graph(graph, self.resolve.unwrap_or(self.color), resolution);
+        }
+    }
+
     /// Tonemap onto the given render target.
     pub fn tonemapping<'node>(
         &self,
@@ -0,0 +1,394 @@
+//! Physically-motivated bloom post-process node.
+//!
+//! Implements bloom as a progressive mip-chain: each mip is produced from the
+//! one above it with a 13-tap downsample filter (a center tap, four
+//! half-pixel-offset inner taps, and eight outer taps forming overlapping 2x2
+//! boxes), with an optional soft-knee threshold applied on the very first
+//! downsample to tame fireflies. The chain is then walked back up from
+//! smallest to largest mip, each step additively blending a 3x3 tent-filtered
+//! sample of the smaller mip onto the next-larger one. The final full
+//! resolution mip is lerped into the scene color by [`BloomRoutine::intensity`].
+//!
+//! This is the dual-filter technique popularized by Jorge Jimenez's "Next
+//! Generation Post Processing in Call of Duty: Advanced Warfare" talk, chosen
+//! because a single small mipped render target is enough to hold the whole
+//! chain.
+//!
+//! Requires [`wgpu::Features::PUSH_CONSTANTS`] (with at least 16 bytes in the
+//! fragment stage) on the device passed to [`BloomRoutine::new`].
+
+use glam::UVec2;
+use rend3::{
+    graph::{
+        DataHandle, NodeResourceUsage, RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetDescriptor,
+        RenderTargetHandle,
+    },
+    types::{SampleCount, TextureFormat, TextureUsages},
+    Renderer, ShaderPreProcessor,
+};
+use wgpu::{BindGroup, BindGroupLayout, RenderPipeline, Sampler};
+
+/// Minimum mip size, in pixels, to stop the bloom chain at.
+const MIN_MIP_SIZE: u32 = 2;
+
+/// Size, in bytes, of the fragment push-constant block shared by all three
+/// passes (one `vec4<f32>`, only partially used by each).
+const PUSH_CONSTANT_SIZE: u32 = 16;
+
+/// Renders a [`BloomRoutine`] into the render graph.
+///
+/// Holds the pipelines and layouts needed to do the threshold/downsample,
+/// additive tent-filtered upsample, and final composite passes; the
+/// mip-chain render target itself is allocated fresh each frame in
+/// [`BloomRoutine::add_to_graph`] since it depends on the output resolution.
+pub struct BloomRoutine {
+    downsample_pipeline: RenderPipeline,
+    upsample_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+    bgl: BindGroupLayout,
+    sampler: Sampler,
+    /// Soft-knee threshold below which pixels don't contribute to bloom.
+    pub threshold: f32,
+    /// Softness of the [`Self::threshold`] transition, in the range `0..=1`.
+    pub knee: f32,
+    /// Radius, in texels of the source (smaller) mip, of the upsample tent filter.
+    pub filter_radius: f32,
+    /// How strongly the bloom result is lerped into the scene color.
+    pub intensity: f32,
+}
+
+impl BloomRoutine {
+    pub fn new(renderer: &Renderer, spp: &ShaderPreProcessor) -> Self {
+        profiling::scope!("BloomRoutine::new");
+
+        let bgl = renderer.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("bloom bgl"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let source = spp
+            .render_shader("bloom.wgsl", &(), None)
+            .expect("failed to preprocess bloom.wgsl");
+        let sm = renderer.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("bloom"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let pll = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("bloom pll"),
+            bind_group_layouts: &[&bgl],
+            push_constant_ranges: &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::FRAGMENT,
+                range: 0..PUSH_CONSTANT_SIZE,
+            }],
+        });
+
+        let make_pipeline = |label, fs_entry, blend| {
+            renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pll),
+                vertex: wgpu::VertexState {
+                    module: &sm,
+                    entry_point: "vs_fullscreen",
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &sm,
+                    entry_point: fs_entry,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: TextureFormat::Rgba16Float,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            })
+        };
+
+        // Downsample always writes a fresh mip, so it needs no blending.
+        let downsample_pipeline = make_pipeline("bloom downsample", "fs_downsample", None);
+        // Upsample must *accumulate* the tent-filtered sample from the smaller mip
+        // onto the next-larger one, so it needs additive blending; alpha blending
+        // would instead attenuate the destination on every level and lose energy
+        // going up the chain.
+        let upsample_pipeline = make_pipeline(
+            "bloom upsample",
+            "fs_upsample",
+            Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::REPLACE,
+            }),
+        );
+        // The final composite lerps the bloom result into the scene color by
+        // `intensity`, which is a genuine alpha blend, distinct from the additive
+        // upsample above.
+        let composite_pipeline = make_pipeline("bloom composite", "fs_composite", Some(wgpu::BlendState::ALPHA_BLENDING));
+
+        let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("bloom sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            downsample_pipeline,
+            upsample_pipeline,
+            composite_pipeline,
+            bgl,
+            sampler,
+            threshold: 1.0,
+            knee: 0.2,
+            filter_radius: 1.0,
+            intensity: 0.04,
+        }
+    }
+
+    /// Add the bloom pass to the graph, reading from and writing back into
+    /// `hdr_color` in place.
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        hdr_color: RenderTargetHandle,
+        resolution: UVec2,
+    ) {
+        let mip_count = mip_chain_len(resolution);
+        if mip_count == 0 {
+            return;
+        }
+
+        let mips = graph.add_render_target(RenderTargetDescriptor {
+            label: Some("bloom mip chain".into()),
+            resolution,
+            depth: 1,
+            mip_levels: Some(mip_count),
+            samples: SampleCount::One,
+            format: TextureFormat::Rgba16Float,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        });
+
+        // Downsample: mip 0 (threshold applied) comes from the scene color, every
+        // following mip comes from the mip above it.
+        self.add_downsample(graph, hdr_color, mips, 0);
+        for mip in 1..mip_count {
+            self.add_downsample(graph, mips.set_mips(mip - 1..mip), mips, mip);
+        }
+
+        // Upsample: walk back up from the smallest mip, additively blending each
+        // mip onto the next-larger one.
+        for mip in (1..mip_count).rev() {
+            self.add_upsample(graph, mips.set_mips(mip..mip + 1), mips.set_mips(mip - 1..mip));
+        }
+
+        // Final lerp of the full-res bloom mip into the scene color.
+        self.add_composite(graph, mips.set_mips(0..1), hdr_color);
+    }
+
+    fn add_downsample<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        src: RenderTargetHandle,
+        mips: RenderTargetHandle,
+        dst_mip: u32,
+    ) {
+        let mut builder = graph.add_node("Bloom Downsample");
+        let src_handle = builder.add_render_target(src, NodeResourceUsage::Input);
+        let dst_handle = builder.add_render_target(mips.set_mips(dst_mip..dst_mip + 1), NodeResourceUsage::Output);
+        let bg_handle: DataHandle<BindGroup> = graph.add_data();
+        let threshold = self.threshold;
+        let knee = self.knee;
+        let apply_threshold = dst_mip == 0;
+
+        builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: dst_handle,
+                clear: wgpu::Color::BLACK,
+                resolve: None,
+            }],
+            depth_stencil: None,
+        });
+
+        builder.build(move |ctx| {
+            let src_tex = ctx.graph_data.get_render_target(src_handle);
+            let bg = ctx.renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bloom downsample bg"),
+                layout: &self.bgl,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(src_tex),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+            ctx.graph_data.set_data(bg_handle, Some(bg));
+
+            let rpass = ctx.encoder_or_pass.get_rpass(ctx.data_core);
+            rpass.set_pipeline(&self.downsample_pipeline);
+            rpass.set_push_constants(
+                wgpu::ShaderStages::FRAGMENT,
+                0,
+                bytemuck::bytes_of(&[threshold, knee, apply_threshold as u32 as f32, 0.0]),
+            );
+            rpass.set_bind_group(0, ctx.graph_data.get_data(ctx.temps, bg_handle).unwrap(), &[]);
+            rpass.draw(0..3, 0..1);
+        });
+    }
+
+    fn add_upsample<'node>(&'node self, graph: &mut RenderGraph<'node>, src: RenderTargetHandle, dst: RenderTargetHandle) {
+        let mut builder = graph.add_node("Bloom Upsample");
+        let src_handle = builder.add_render_target(src, NodeResourceUsage::Input);
+        let dst_handle = builder.add_render_target(dst, NodeResourceUsage::InputOutput);
+        let bg_handle: DataHandle<BindGroup> = graph.add_data();
+        let filter_radius = self.filter_radius;
+
+        builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: dst_handle,
+                clear: wgpu::Color::TRANSPARENT,
+                resolve: None,
+            }],
+            depth_stencil: None,
+        });
+
+        builder.build(move |ctx| {
+            let src_tex = ctx.graph_data.get_render_target(src_handle);
+            let bg = ctx.renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bloom upsample bg"),
+                layout: &self.bgl,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(src_tex),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+            ctx.graph_data.set_data(bg_handle, Some(bg));
+
+            let rpass = ctx.encoder_or_pass.get_rpass(ctx.data_core);
+            rpass.set_pipeline(&self.upsample_pipeline);
+            rpass.set_push_constants(
+                wgpu::ShaderStages::FRAGMENT,
+                0,
+                bytemuck::bytes_of(&[filter_radius, 0.0, 0.0, 0.0]),
+            );
+            rpass.set_bind_group(0, ctx.graph_data.get_data(ctx.temps, bg_handle).unwrap(), &[]);
+            rpass.draw(0..3, 0..1);
+        });
+    }
+
+    fn add_composite<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        bloom_mip0: RenderTargetHandle,
+        hdr_color: RenderTargetHandle,
+    ) {
+        let mut builder = graph.add_node("Bloom Composite");
+        let src_handle = builder.add_render_target(bloom_mip0, NodeResourceUsage::Input);
+        let dst_handle = builder.add_render_target(hdr_color, NodeResourceUsage::InputOutput);
+        let bg_handle: DataHandle<BindGroup> = graph.add_data();
+        let intensity = self.intensity;
+
+        builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: dst_handle,
+                clear: wgpu::Color::TRANSPARENT,
+                resolve: None,
+            }],
+            depth_stencil: None,
+        });
+
+        builder.build(move |ctx| {
+            let src_tex = ctx.graph_data.get_render_target(src_handle);
+            let bg = ctx.renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("bloom composite bg"),
+                layout: &self.bgl,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(src_tex),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                ],
+            });
+            ctx.graph_data.set_data(bg_handle, Some(bg));
+
+            // Lerp mip 0 of the bloom chain into the scene color by `intensity`.
+            let rpass = ctx.encoder_or_pass.get_rpass(ctx.data_core);
+            rpass.set_pipeline(&self.composite_pipeline);
+            rpass.set_push_constants(wgpu::ShaderStages::FRAGMENT, 0, bytemuck::bytes_of(&[intensity, 0.0, 0.0, 0.0]));
+            rpass.set_bind_group(0, ctx.graph_data.get_data(ctx.temps, bg_handle).unwrap(), &[]);
+            rpass.draw(0..3, 0..1);
+        });
+    }
+}
+
+/// How many mips a bloom chain for the given resolution should have, stopping
+/// once a mip would be smaller than [`MIN_MIP_SIZE`] on its shortest side.
+fn mip_chain_len(resolution: UVec2) -> u32 {
+    let shortest = resolution.x.min(resolution.y).max(1);
+    let mut mips = 1;
+    let mut size = shortest;
+    while size / 2 >= MIN_MIP_SIZE {
+        size /= 2;
+        mips += 1;
+    }
+    mips
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chain_len_halves_until_below_min_mip_size() {
+        assert_eq!(mip_chain_len(UVec2::new(256, 256)), 8);
+        assert_eq!(mip_chain_len(UVec2::new(1920, 1080)), 10);
+    }
+
+    #[test]
+    fn mip_chain_len_uses_the_shortest_side() {
+        assert_eq!(mip_chain_len(UVec2::new(256, 4)), mip_chain_len(UVec2::new(4, 4)));
+    }
+
+    #[test]
+    fn mip_chain_len_is_never_zero_even_at_degenerate_resolutions() {
+        assert_eq!(mip_chain_len(UVec2::new(0, 0)), 1);
+        assert_eq!(mip_chain_len(UVec2::new(1, 1)), 1);
+        assert_eq!(mip_chain_len(UVec2::new(3, 3)), 1);
+    }
+}
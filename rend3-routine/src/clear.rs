@@ -14,28 +14,62 @@ pub fn add_clear_to_graph(
     depth: RenderTargetHandle,
     clear_color: Vec4,
     depth_clear: f32,
+) {
+    add_clear_to_graph_with_extra_targets(graph, color, resolve, &[], depth, clear_color, depth_clear)
+}
+
+/// Same as [`add_clear_to_graph`], but also clears any number of additional
+/// color targets (e.g. G-buffer or normal/motion-vector prepass attachments)
+/// to [`Vec4::ZERO`] in the same renderpass. Each extra target may carry its
+/// own resolve target, exactly like `color`/`resolve` — needed so that
+/// multisampled attachments still have a well-defined single-sample value for
+/// background pixels that no later pass ever touches.
+pub fn add_clear_to_graph_with_extra_targets(
+    graph: &mut RenderGraph<'_>,
+    color: Option<RenderTargetHandle>,
+    resolve: Option<RenderTargetHandle>,
+    extra_color_targets: &[(RenderTargetHandle, Option<RenderTargetHandle>)],
+    depth: RenderTargetHandle,
+    clear_color: Vec4,
+    depth_clear: f32,
 ) {
     let mut builder = graph.add_node("Clear");
 
     let hdr_color_handle = builder.add_optional_render_target(color, NodeResourceUsage::Output);
     let hdr_resolve = builder.add_optional_render_target(resolve, NodeResourceUsage::Output);
+    let extra_color_handles: Vec<_> = extra_color_targets
+        .iter()
+        .map(|&(target, resolve)| {
+            (
+                builder.add_render_target(target, NodeResourceUsage::Output),
+                builder.add_optional_render_target(resolve, NodeResourceUsage::Output),
+            )
+        })
+        .collect();
     let hdr_depth_handle = builder.add_render_target(depth, NodeResourceUsage::Output);
 
+    let mut targets = if let Some(hdr_color_handle) = hdr_color_handle {
+        vec![RenderPassTarget {
+            color: hdr_color_handle,
+            clear: wgpu::Color {
+                r: clear_color.x as f64,
+                g: clear_color.y as f64,
+                b: clear_color.z as f64,
+                a: clear_color.w as f64,
+            },
+            resolve: hdr_resolve,
+        }]
+    } else {
+        vec![]
+    };
+    targets.extend(extra_color_handles.into_iter().map(|(handle, resolve)| RenderPassTarget {
+        color: handle,
+        clear: wgpu::Color::TRANSPARENT,
+        resolve,
+    }));
+
     let _rpass_handle = builder.add_renderpass(RenderPassTargets {
-        targets: if let Some(hdr_color_handle) = hdr_color_handle {
-            vec![RenderPassTarget {
-                color: hdr_color_handle,
-                clear: wgpu::Color {
-                    r: clear_color.x as f64,
-                    g: clear_color.y as f64,
-                    b: clear_color.z as f64,
-                    a: clear_color.w as f64,
-                },
-                resolve: hdr_resolve,
-            }]
-        } else {
-            vec![]
-        },
+        targets,
         depth_stencil: Some(RenderPassDepthTarget {
             target: hdr_depth_handle,
             depth_clear: Some(depth_clear),
@@ -0,0 +1,168 @@
+//! Full-screen pass that unpacks the deferred G-buffer and shades every
+//! covered pixel exactly once, the counterpart to the inline shading the
+//! forward path does per-draw.
+//!
+//! See `gbuffer.wgsl`/`deferred_lighting.wgsl` for the packing layout and the
+//! shading itself; this module is just the pipeline/graph-node plumbing
+//! around it, mirroring [`crate::bloom::BloomRoutine`]'s shape.
+
+use glam::Mat4;
+use rend3::{
+    graph::{DataHandle, NodeResourceUsage, RenderGraph, RenderPassTarget, RenderPassTargets, RenderTargetHandle},
+    Renderer, ShaderPreProcessor,
+};
+use wgpu::{BindGroup, BindGroupLayout, RenderPipeline};
+
+use crate::common::WholeFrameInterfaces;
+
+const PUSH_CONSTANT_SIZE: u32 = std::mem::size_of::<Mat4>() as u32;
+
+pub struct DeferredLightingRoutine {
+    pipeline: RenderPipeline,
+    gbuffer_bgl: BindGroupLayout,
+}
+
+impl DeferredLightingRoutine {
+    pub fn new(renderer: &Renderer, spp: &ShaderPreProcessor, interfaces: &WholeFrameInterfaces) -> Self {
+        profiling::scope!("DeferredLightingRoutine::new");
+
+        let gbuffer_bgl = renderer
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("deferred lighting gbuffer bgl"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Uint,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let source = spp
+            .render_shader("deferred_lighting.wgsl", &(), None)
+            .expect("failed to preprocess deferred_lighting.wgsl");
+        let sm = renderer.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("deferred lighting"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let pll = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("deferred lighting pll"),
+            // Group 0 is this pass's own gbuffer/depth inputs; 1 and 2 are the
+            // shared shadow/forward uniform bind groups every shaded pass uses,
+            // so the lighting math reads the exact same lights/shadow data the
+            // forward path would have.
+            bind_group_layouts: &[&gbuffer_bgl, &interfaces.shadow_bgl, &interfaces.forward_bgl],
+            push_constant_ranges: &[wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::FRAGMENT,
+                range: 0..PUSH_CONSTANT_SIZE,
+            }],
+        });
+
+        let pipeline = renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("deferred lighting"),
+            layout: Some(&pll),
+            vertex: wgpu::VertexState {
+                module: &sm,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &sm,
+                entry_point: "fs_deferred_lighting",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: wgpu::TextureFormat::Rgba16Float,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        Self { pipeline, gbuffer_bgl }
+    }
+
+    /// Unpack `gbuffer`, shade every pixel it covers (using `depth` to
+    /// reconstruct world position and `shadow_uniform_bg`/`forward_uniform_bg`
+    /// for lighting), and write the result into `color`/`resolve`. Pixels the
+    /// gbuffer pass never wrote (depth still at the far-plane clear value)
+    /// are left untouched for the skybox pass to fill in afterwards.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_to_graph<'node>(
+        &'node self,
+        graph: &mut RenderGraph<'node>,
+        gbuffer: RenderTargetHandle,
+        depth: RenderTargetHandle,
+        color: RenderTargetHandle,
+        resolve: Option<RenderTargetHandle>,
+        shadow_uniform_bg: DataHandle<BindGroup>,
+        forward_uniform_bg: DataHandle<BindGroup>,
+        inv_view_proj: Mat4,
+    ) {
+        let mut builder = graph.add_node("Deferred Lighting");
+        let gbuffer_handle = builder.add_render_target(gbuffer, NodeResourceUsage::Input);
+        let depth_handle = builder.add_render_target(depth, NodeResourceUsage::Input);
+        let color_handle = builder.add_render_target(color, NodeResourceUsage::Output);
+        let resolve_handle = builder.add_optional_render_target(resolve, NodeResourceUsage::Output);
+        let gbuffer_bg_handle: DataHandle<BindGroup> = graph.add_data();
+
+        builder.add_renderpass(RenderPassTargets {
+            targets: vec![RenderPassTarget {
+                color: color_handle,
+                clear: wgpu::Color::TRANSPARENT,
+                resolve: resolve_handle,
+            }],
+            depth_stencil: None,
+        });
+
+        builder.build(move |ctx| {
+            let gbuffer_view = ctx.graph_data.get_render_target(gbuffer_handle);
+            let depth_view = ctx.graph_data.get_render_target(depth_handle);
+            let bg = ctx.renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("deferred lighting gbuffer bg"),
+                layout: &self.gbuffer_bgl,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(gbuffer_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(depth_view),
+                    },
+                ],
+            });
+            ctx.graph_data.set_data(gbuffer_bg_handle, Some(bg));
+
+            let shadow_bg = ctx.graph_data.get_data(ctx.temps, shadow_uniform_bg).unwrap();
+            let forward_bg = ctx.graph_data.get_data(ctx.temps, forward_uniform_bg).unwrap();
+
+            let rpass = ctx.encoder_or_pass.get_rpass(ctx.data_core);
+            rpass.set_pipeline(&self.pipeline);
+            rpass.set_push_constants(wgpu::ShaderStages::FRAGMENT, 0, bytemuck::bytes_of(&inv_view_proj));
+            rpass.set_bind_group(0, ctx.graph_data.get_data(ctx.temps, gbuffer_bg_handle).unwrap(), &[]);
+            rpass.set_bind_group(1, shadow_bg, &[]);
+            rpass.set_bind_group(2, forward_bg, &[]);
+            rpass.draw(0..3, 0..1);
+        });
+    }
+}
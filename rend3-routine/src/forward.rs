@@ -0,0 +1,193 @@
+//! Generic forward-rendering routine shared by the PBR opaque, cutout,
+//! gbuffer-packing, and transparent draws.
+//!
+//! A single [`ForwardRoutine`] knows how to cull-and-draw one material's
+//! pipeline into whatever color/depth targets [`RoutineAddToGraphArgs`]
+//! points it at; [`pbr::PbrRoutine`](crate::pbr::PbrRoutine) holds one
+//! instance per shading variant (opaque, cutout, their gbuffer-packing
+//! counterparts, and blend) and [`BaseRenderGraph`](crate::base::BaseRenderGraph)
+//! picks which ones to invoke.
+
+use std::sync::Arc;
+
+use rend3::{
+    graph::{DataHandle, NodeResourceUsage, RenderGraph, RenderPassDepthTarget, RenderPassTarget, RenderPassTargets, RenderTargetHandle},
+    types::SampleCount,
+};
+use wgpu::BindGroup;
+
+use crate::{common::CameraIndex, culling::DrawCallSet};
+
+/// Bind group index of the first entry in [`RoutineAddToGraphArgs::extra_bgs`].
+/// Groups 0-2 are always the whole-frame uniforms, the material's own data,
+/// and the per-object transforms (see `pbr_forward.wgsl`), so extra bind
+/// groups start right after those.
+pub const EXTRA_BG_START_INDEX: u32 = 3;
+
+/// Which color target(s) a [`crate::common::MaterialPipelines::new`] caller
+/// wants its pipeline built against, since the same material shader source
+/// can back very different attachment setups (HDR forward color, HDR color
+/// plus normal/motion attachments, depth-only, or the packed gbuffer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorTargetConfig {
+    /// Single HDR color target, alpha-blended. Used by [`crate::pbr::PbrRoutine::blend_routine`].
+    HdrBlended,
+    /// HDR color target plus the `normal`/`motion_vector` attachments
+    /// `opaque.wgsl`/`cutout.wgsl` write, none of them blended (the forward
+    /// opaque/cutout passes always fully overwrite every covered pixel). Used
+    /// by [`crate::pbr::PbrRoutine::opaque_routine`]/[`crate::pbr::PbrRoutine::cutout_routine`].
+    HdrWithAttachments,
+    /// No color targets at all, depth-only. Used by the shadow/depth-prepass routines.
+    DepthOnly,
+    /// Single unblendable `Rgba32Uint` target: the packed gbuffer. Used by
+    /// [`crate::pbr::PbrRoutine::opaque_gbuffer_routine`]/[`crate::pbr::PbrRoutine::cutout_gbuffer_routine`].
+    Gbuffer,
+}
+
+/// Arguments to [`ForwardRoutine::add_forward_to_graph`].
+///
+/// Grouped into a struct (rather than a long parameter list) because most
+/// call sites in [`BaseRenderGraph::add_to_graph`](crate::base::BaseRenderGraph::add_to_graph)
+/// only vary a handful of these between passes.
+pub struct RoutineAddToGraphArgs<'a, 'node> {
+    pub graph: &'a mut RenderGraph<'node>,
+    pub whole_frame_uniform_bg: DataHandle<BindGroup>,
+    /// `None` means "everything visible, no prior culling pass" (used for the
+    /// predicted-triangle passes); `Some` draws exactly the culled set.
+    pub culling_output_handle: Option<DataHandle<Arc<DrawCallSet>>>,
+    pub per_material: &'node crate::common::PerMaterialArchive,
+    /// Auxiliary bind groups registered via
+    /// [`BaseRenderGraph::register_extra_bg`](crate::base::BaseRenderGraph::register_extra_bg),
+    /// bound starting at [`EXTRA_BG_START_INDEX`] (after the whole-frame,
+    /// material, and per-object bind groups, which occupy 0-2). A material shader that wants to read
+    /// one declares `@group(EXTRA_BG_START_INDEX + n)` for the `n`th entry
+    /// here.
+    pub extra_bgs: Option<&'node [DataHandle<BindGroup>]>,
+    pub label: &'a str,
+    pub samples: SampleCount,
+    pub camera: CameraIndex,
+    /// Primary color attachment. `None` for shadow passes, which only write depth.
+    pub color: Option<RenderTargetHandle>,
+    pub resolve: Option<RenderTargetHandle>,
+    /// World-space normal attachment, written by the PBR fragment shader's
+    /// `normal` output alongside `color`. `None` for passes that don't need
+    /// it (shadows, transparents, and the gbuffer passes pack normal into
+    /// `color` instead).
+    pub normal: Option<RenderTargetHandle>,
+    pub normal_resolve: Option<RenderTargetHandle>,
+    /// Screen-space motion vector attachment, written by the PBR fragment
+    /// shader's `motion_vector` output alongside `color`.
+    pub motion_vectors: Option<RenderTargetHandle>,
+    pub motion_vectors_resolve: Option<RenderTargetHandle>,
+    pub depth: RenderTargetHandle,
+}
+
+/// A single material pipeline drawn with [`add_forward_to_graph`](Self::add_forward_to_graph).
+///
+/// The forward opaque/cutout variants share `forward_attachments.wgsl`'s
+/// `FragmentOutput` layout: `@location(0)` is `color`, `@location(1)` is
+/// `normal`, `@location(2)` is `motion_vector`; the blend variant writes a
+/// single HDR color target. The gbuffer-packing variants
+/// are separate [`ForwardRoutine`] instances built from a different shader
+/// (`opaque_gbuffer.wgsl`/`cutout_gbuffer.wgsl`) that instead writes the
+/// bitpacked `GbufferData` (see `gbuffer.wgsl`) to a single `@location(0)`.
+pub struct ForwardRoutine<M> {
+    pub(crate) label: &'static str,
+    pipelines: crate::common::MaterialPipelines<M>,
+}
+
+impl<M: crate::common::Material> ForwardRoutine<M> {
+    pub fn new(label: &'static str, pipelines: crate::common::MaterialPipelines<M>) -> Self {
+        Self { label, pipelines }
+    }
+
+    /// Add this material's draw to the graph.
+    pub fn add_forward_to_graph<'node>(&'node self, args: RoutineAddToGraphArgs<'_, 'node>) {
+        let mut builder = args.graph.add_node(args.label);
+
+        let color_handle = args.color.map(|c| builder.add_render_target(c, NodeResourceUsage::Output));
+        let resolve_handle = args
+            .resolve
+            .map(|r| builder.add_render_target(r, NodeResourceUsage::Output));
+        let normal_handle = args.normal.map(|n| builder.add_render_target(n, NodeResourceUsage::Output));
+        let normal_resolve_handle = args
+            .normal_resolve
+            .map(|n| builder.add_render_target(n, NodeResourceUsage::Output));
+        let motion_handle = args
+            .motion_vectors
+            .map(|m| builder.add_render_target(m, NodeResourceUsage::Output));
+        let motion_resolve_handle = args
+            .motion_vectors_resolve
+            .map(|m| builder.add_render_target(m, NodeResourceUsage::Output));
+        let depth_handle = builder.add_render_target(args.depth, NodeResourceUsage::InputOutput);
+
+        let mut targets = Vec::with_capacity(3);
+        if let Some(color) = color_handle {
+            targets.push(RenderPassTarget {
+                color,
+                clear: wgpu::Color::TRANSPARENT,
+                resolve: resolve_handle,
+            });
+        }
+        if let Some(normal) = normal_handle {
+            targets.push(RenderPassTarget {
+                color: normal,
+                clear: wgpu::Color::TRANSPARENT,
+                resolve: normal_resolve_handle,
+            });
+        }
+        if let Some(motion) = motion_handle {
+            targets.push(RenderPassTarget {
+                color: motion,
+                clear: wgpu::Color::TRANSPARENT,
+                resolve: motion_resolve_handle,
+            });
+        }
+
+        builder.add_renderpass(RenderPassTargets {
+            targets,
+            depth_stencil: Some(RenderPassDepthTarget {
+                target: depth_handle,
+                depth_clear: None,
+                stencil_clear: None,
+            }),
+        });
+
+        let whole_frame_uniform_bg = args.whole_frame_uniform_bg;
+        let culling_output_handle = args.culling_output_handle;
+        let per_material = args.per_material;
+        let extra_bgs = args.extra_bgs;
+        let camera = args.camera;
+        let samples = args.samples;
+
+        builder.build(move |ctx| {
+            profiling::scope!(self.label);
+
+            let pipeline = self.pipelines.pipeline_for(samples);
+
+            let rpass = ctx.encoder_or_pass.get_rpass(ctx.data_core);
+            rpass.set_pipeline(pipeline);
+
+            let whole_frame_uniform_bg = ctx.graph_data.get_data(ctx.temps, whole_frame_uniform_bg).unwrap();
+            rpass.set_bind_group(0, whole_frame_uniform_bg, &[]);
+
+            // Bound here, not threaded through `per_material.draw`, so a material
+            // shader can read them without `PerMaterialArchive` needing to know
+            // anything about this routine's auxiliary resources.
+            if let Some(extra_bgs) = extra_bgs {
+                for (i, &handle) in extra_bgs.iter().enumerate() {
+                    if let Some(bg) = ctx.graph_data.get_data(ctx.temps, handle) {
+                        rpass.set_bind_group(EXTRA_BG_START_INDEX + i as u32, bg, &[]);
+                    }
+                }
+            }
+
+            let draws = match culling_output_handle {
+                Some(handle) => ctx.graph_data.get_data(ctx.temps, handle).cloned(),
+                None => None,
+            };
+
+            per_material.draw(rpass, draws.as_deref(), camera, ctx);
+        });
+    }
+}
@@ -0,0 +1,108 @@
+//! The PBR material and its routines: depth-only (shadow), forward
+//! opaque/cutout/transparent, the gbuffer-packing opaque/cutout variants, and
+//! the deferred lighting pass that shades what they pack.
+//!
+//! [`PbrRoutine::opaque_gbuffer_routine`], [`PbrRoutine::cutout_gbuffer_routine`],
+//! and [`PbrRoutine::deferred_lighting_routine`] back
+//! [`crate::base::ShadingMode::Deferred`]: the gbuffer routines are ordinary
+//! [`ForwardRoutine`]s pointed at the packed G-buffer target instead of the
+//! HDR color target, and `deferred_lighting_routine` is the full-screen pass
+//! that unpacks and shades it.
+
+use rend3::{Renderer, ShaderPreProcessor};
+
+use crate::{
+    common::{Material, MaterialPipelines, PerMaterialArchive, WholeFrameInterfaces},
+    deferred_lighting::DeferredLightingRoutine,
+    forward::{ColorTargetConfig, ForwardRoutine},
+    hi_z::HiZRoutine,
+};
+
+/// Marker type identifying the PBR material to the generic culling,
+/// skinning, and forward-routine machinery.
+pub struct PbrMaterial;
+
+impl Material for PbrMaterial {}
+
+/// All the routines needed to render PBR materials, forward or deferred.
+pub struct PbrRoutine {
+    pub per_material: PerMaterialArchive,
+
+    pub opaque_depth: ForwardRoutine<PbrMaterial>,
+    pub cutout_depth: ForwardRoutine<PbrMaterial>,
+
+    pub opaque_routine: ForwardRoutine<PbrMaterial>,
+    pub cutout_routine: ForwardRoutine<PbrMaterial>,
+    pub blend_routine: ForwardRoutine<PbrMaterial>,
+
+    /// Packs opaque/cutout surfaces (`GbufferData`, see `gbuffer.wgsl`) into [`crate::base::BaseRenderGraphIntermediateState::gbuffer`]
+    /// instead of shading inline. Used by [`crate::base::ShadingMode::Deferred`].
+    pub opaque_gbuffer_routine: ForwardRoutine<PbrMaterial>,
+    pub cutout_gbuffer_routine: ForwardRoutine<PbrMaterial>,
+    /// Unpacks the gbuffer and shades every pixel it covers. The
+    /// [`crate::base::ShadingMode::Deferred`] counterpart to the inline
+    /// shading [`Self::opaque_routine`]/[`Self::cutout_routine`] do.
+    pub deferred_lighting_routine: DeferredLightingRoutine,
+
+    pub hi_z: HiZRoutine,
+}
+
+impl PbrRoutine {
+    pub fn new(renderer: &Renderer, spp: &ShaderPreProcessor, interfaces: &WholeFrameInterfaces) -> Self {
+        profiling::scope!("PbrRoutine::new");
+
+        let per_material = PerMaterialArchive::new::<PbrMaterial>(renderer, spp);
+
+        let opaque_depth = ForwardRoutine::new(
+            "PBR Opaque Depth",
+            MaterialPipelines::new::<PbrMaterial>(renderer, spp, "depth.wgsl", ColorTargetConfig::DepthOnly),
+        );
+        let cutout_depth = ForwardRoutine::new(
+            "PBR Cutout Depth",
+            MaterialPipelines::new::<PbrMaterial>(renderer, spp, "depth_cutout.wgsl", ColorTargetConfig::DepthOnly),
+        );
+
+        let opaque_routine = ForwardRoutine::new(
+            "PBR Opaque",
+            MaterialPipelines::new::<PbrMaterial>(renderer, spp, "opaque.wgsl", ColorTargetConfig::HdrWithAttachments),
+        );
+        let cutout_routine = ForwardRoutine::new(
+            "PBR Cutout",
+            MaterialPipelines::new::<PbrMaterial>(renderer, spp, "cutout.wgsl", ColorTargetConfig::HdrWithAttachments),
+        );
+        let blend_routine = ForwardRoutine::new(
+            "PBR Blend",
+            MaterialPipelines::new::<PbrMaterial>(renderer, spp, "blend.wgsl", ColorTargetConfig::HdrBlended),
+        );
+
+        // Gbuffer-packing variants share the same material bind groups and vertex
+        // stage as the forward routines above (see `pbr_forward.wgsl`), but their
+        // fragment shader packs the surface with `pack_gbuffer` (see `gbuffer.wgsl`)
+        // into a single unblendable `Rgba32Uint` target instead of shading it, so
+        // they need `ColorTargetConfig::Gbuffer` rather than the HDR formats above.
+        let opaque_gbuffer_routine = ForwardRoutine::new(
+            "PBR Opaque Gbuffer",
+            MaterialPipelines::new::<PbrMaterial>(renderer, spp, "opaque_gbuffer.wgsl", ColorTargetConfig::Gbuffer),
+        );
+        let cutout_gbuffer_routine = ForwardRoutine::new(
+            "PBR Cutout Gbuffer",
+            MaterialPipelines::new::<PbrMaterial>(renderer, spp, "cutout_gbuffer.wgsl", ColorTargetConfig::Gbuffer),
+        );
+        let deferred_lighting_routine = DeferredLightingRoutine::new(renderer, spp, interfaces);
+
+        let hi_z = HiZRoutine::new(renderer, spp);
+
+        Self {
+            per_material,
+            opaque_depth,
+            cutout_depth,
+            opaque_routine,
+            cutout_routine,
+            blend_routine,
+            opaque_gbuffer_routine,
+            cutout_gbuffer_routine,
+            deferred_lighting_routine,
+            hi_z,
+        }
+    }
+}
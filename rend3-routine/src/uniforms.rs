@@ -0,0 +1,64 @@
+//! Builds the whole-frame uniform bind groups (`shadow_uniform_bg` and
+//! `forward_uniform_bg`) that every shadow/forward draw binds at group 0.
+
+use glam::{UVec2, Vec4};
+use rend3::{
+    graph::{DataHandle, NodeResourceUsage, RenderGraph, RenderTargetHandle},
+    types::TextureFormat,
+};
+use wgpu::BindGroup;
+
+use crate::common::{Samplers, WholeFrameInterfaces};
+
+/// Add the nodes that build `shadow_uniform_bg` and `forward_uniform_bg` to
+/// the graph.
+///
+/// `normal`/`motion_vectors` must already be single-sample (the caller
+/// resolves them down from the multisampled render targets first): a
+/// `texture_2d` binding can't read a multisampled texture directly, and
+/// [`crate::base::BaseRenderGraphIntermediateState`] keeps the resolved
+/// copies around precisely so they can be handed to this bind group.
+#[allow(clippy::too_many_arguments)]
+pub fn add_to_graph<'node>(
+    graph: &mut RenderGraph<'node>,
+    shadow_uniform_bg: DataHandle<BindGroup>,
+    forward_uniform_bg: DataHandle<BindGroup>,
+    shadow: RenderTargetHandle,
+    normal: RenderTargetHandle,
+    motion_vectors: RenderTargetHandle,
+    interfaces: &'node WholeFrameInterfaces,
+    samplers: &'node Samplers,
+    ambient: Vec4,
+    resolution: UVec2,
+) {
+    let mut builder = graph.add_node("Shadow Uniforms");
+    let shadow_handle = builder.add_render_target(shadow, NodeResourceUsage::Input);
+    builder.build(move |ctx| {
+        let shadow_view = ctx.graph_data.get_render_target(shadow_handle);
+        let bg = interfaces.build_shadow_uniform_bg(&ctx.renderer.device, shadow_view, samplers);
+        ctx.graph_data.set_data(shadow_uniform_bg, Some(bg));
+    });
+
+    let mut builder = graph.add_node("Forward Uniforms");
+    let normal_handle = builder.add_render_target(normal, NodeResourceUsage::Input);
+    let motion_handle = builder.add_render_target(motion_vectors, NodeResourceUsage::Input);
+    builder.build(move |ctx| {
+        let normal_view = ctx.graph_data.get_render_target(normal_handle);
+        let motion_view = ctx.graph_data.get_render_target(motion_handle);
+        let bg = interfaces.build_forward_uniform_bg(
+            &ctx.renderer.device,
+            normal_view,
+            motion_view,
+            samplers,
+            ambient,
+            resolution,
+        );
+        ctx.graph_data.set_data(forward_uniform_bg, Some(bg));
+    });
+}
+
+/// Format the normal prepass target is allocated with; kept here since it's
+/// part of this module's binding contract with [`WholeFrameInterfaces`].
+pub const NORMAL_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Format the motion vector prepass target is allocated with.
+pub const MOTION_VECTOR_FORMAT: TextureFormat = TextureFormat::Rg16Float;